@@ -1,22 +1,116 @@
 use std::env;
 use std::sync::{Arc};
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::thread;
 use std::time::Duration;
 use git2::{Repository, Commit, Signature};
 use sha1::{Sha1, Digest};
+use sha2::Sha256;
 use num_cpus;
 use regex::Regex;
+use rand::Rng;
+use crossbeam_deque::{Injector, Steal, Stealer, Worker};
+
+mod net;
+
+// Width of each nonce range handed out by the generator. Wide enough that a
+// worker can chew on it for a while without going back to the injector, but
+// narrow enough that stragglers near the end of a run don't stall everyone
+// else waiting on one giant chunk.
+pub(crate) const CHUNK_SIZE: u64 = 1_000_000;
+
+// How often (in nonces) a worker bumps the high-water mark while still in
+// the middle of a range, so a Ctrl-C checkpoint reflects in-flight progress
+// rather than only whole completed ranges -- otherwise a resume can redo up
+// to CHUNK_SIZE worth of hashing per thread for no reason.
+const PROGRESS_REPORT_INTERVAL: u64 = 10_000;
+
+// A contiguous, half-open range of nonces ([start, end)) handed out by the
+// generator thread and passed between workers via the injector/stealers.
+#[derive(Copy, Clone)]
+pub(crate) struct NonceRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+// Which object hash a repository uses. Git defaults to SHA-1, but
+// repositories created with `git init --object-format=sha256` (or the
+// `extensions.objectformat` config set to "sha256") hash loose objects with
+// SHA-256 instead, and vanity-nonce searches need to match that exactly.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum HashAlgo {
+    Sha1,
+    Sha256,
+}
+
+impl HashAlgo {
+    // Width, in hex characters, of a full digest under this algorithm --
+    // used to bound-check `-h`/`-m` so a too-long prefix is rejected early
+    // instead of silently never matching.
+    fn hex_width(&self) -> usize {
+        match self {
+            HashAlgo::Sha1 => 40,
+            HashAlgo::Sha256 => 64,
+        }
+    }
+
+    fn parse(value: &str) -> Option<HashAlgo> {
+        match value {
+            "sha1" => Some(HashAlgo::Sha1),
+            "sha256" => Some(HashAlgo::Sha256),
+            _ => None,
+        }
+    }
+
+    // Auto-detects the repo's object format from `extensions.objectformat`.
+    // Git only sets that key for SHA-256 repos, so an unset/unreadable key
+    // means the (default) SHA-1 format.
+    fn detect(repo: &Repository) -> HashAlgo {
+        repo.config()
+            .and_then(|config| config.get_string("extensions.objectformat"))
+            .ok()
+            .and_then(|value| HashAlgo::parse(&value))
+            .unwrap_or(HashAlgo::Sha1)
+    }
+
+    fn digest_hex(&self, data: &str) -> String {
+        match self {
+            HashAlgo::Sha1 => {
+                let mut hasher = Sha1::new();
+                hasher.update(data);
+                format!("{:x}", hasher.finalize())
+            }
+            HashAlgo::Sha256 => {
+                let mut hasher = Sha256::new();
+                hasher.update(data);
+                format!("{:x}", hasher.finalize())
+            }
+        }
+    }
+}
+
+// Bundles the pieces of a commit that every nonce check/amend needs together
+// -- raw_header, raw_message, committer_name, and hash_algo are threaded
+// through nearly every function in this module, and passing them one by one
+// is what pushed several of those functions past clippy's argument limit.
+// Cheap to clone: the strings are Arc'd and HashAlgo is Copy.
+#[derive(Clone)]
+pub(crate) struct CommitContext {
+    pub raw_header: Arc<String>,
+    pub raw_message: Arc<String>,
+    pub committer_name: Arc<String>,
+    pub hash_algo: HashAlgo,
+}
 
-fn check_commit_with_nonce(
-    raw_header: &str,
-    raw_message: &str,
-    committer_name: &str,
+pub(crate) fn check_commit_with_nonce(
+    ctx: &CommitContext,
     nonce: u64,
     desired_hash_start: &str,
     hidden_message: Option<&str>, // New parameter for hidden message
 ) -> bool {
-    let mut hasher = Sha1::new();
+    let raw_header = ctx.raw_header.as_str();
+    let raw_message = ctx.raw_message.as_str();
+    let committer_name = ctx.committer_name.as_str();
 
     // Find the position of the committer name in the raw header
     if let Some(pos) = raw_header.rfind(committer_name) {
@@ -37,12 +131,8 @@ fn check_commit_with_nonce(
         let commit_preface = format!("commit {}{}", raw_commit.len(), '\0');
         let full_commit = commit_preface + &raw_commit;
 
-        // Hash the full commit string
-        hasher.update(full_commit);
-        let result = hasher.finalize();
-
-        // Convert the hash to a hexadecimal string
-        let hash_hex = format!("{:x}", result);
+        // Hash the full commit string with whichever algorithm this repo uses
+        let hash_hex = ctx.hash_algo.digest_hex(&full_commit);
 
         // Check for both conditions if both are provided
         if let Some(hidden) = hidden_message {
@@ -55,7 +145,23 @@ fn check_commit_with_nonce(
     }
 }
 
-fn build_commit_with_nonce(commit: &Commit, nonce: u64) -> Result<(), git2::Error> {
+fn build_commit_with_nonce(
+    commit: &Commit,
+    nonce: u64,
+    ctx: &CommitContext,
+    desired_hash_start: &str,
+    hidden_message: Option<&str>,
+) -> Result<(), git2::Error> {
+    // Cheap insurance: re-verify against the chosen algorithm before
+    // touching the repo, and refuse to amend if the nonce doesn't produce
+    // the desired hash. This uses the same algorithm as the search, so it
+    // does not catch a --hash that differs from the repo's object format.
+    if !check_commit_with_nonce(ctx, nonce, desired_hash_start, hidden_message) {
+        return Err(git2::Error::from_str(
+            "Refusing to amend: nonce no longer verifies against the chosen hash algorithm",
+        ));
+    }
+
     let committer = commit.committer();
     let committer_name_raw = committer.name().unwrap_or("Unknown");
     let committer_name = sanitize_committer_name(committer_name_raw); // Sanitize the committer name
@@ -80,46 +186,140 @@ fn build_commit_with_nonce(commit: &Commit, nonce: u64) -> Result<(), git2::Erro
     Ok(())
 }
 
+// Runs the nonce-range generator: pushes successive CHUNK_SIZE-wide ranges
+// onto the injector for workers to pop/steal. Stops once a result is found
+// so it doesn't spin forever producing work nobody will consume.
+//
+// `cursor` is shared rather than owned locally so that, in --serve mode, the
+// coordinator can carve leases for remote workers out of the exact same
+// nonce space instead of risking overlap with a second independent counter.
+fn generator_logic(cursor: Arc<AtomicU64>, injector: Arc<Injector<NonceRange>>, shared_result: Arc<AtomicU64>, stop_flag: Arc<AtomicBool>, num_workers: usize) {
+    loop {
+        if shared_result.load(Ordering::SeqCst) != 0 || stop_flag.load(Ordering::SeqCst) {
+            return;
+        }
+
+        // Don't let the backlog grow unbounded if workers fall behind.
+        if injector.len() > num_workers * 4 {
+            thread::sleep(Duration::from_millis(10));
+            continue;
+        }
+
+        let start = cursor.fetch_add(CHUNK_SIZE, Ordering::SeqCst);
+        injector.push(NonceRange {
+            start,
+            end: start + CHUNK_SIZE,
+        });
+    }
+}
+
+// The work-stealing queues a worker thread pulls ranges from: its own local
+// queue, the shared injector, and everyone else's stealers. Bundled together
+// since they're always passed as a set, never individually.
+struct WorkerQueues {
+    local: Worker<NonceRange>,
+    injector: Arc<Injector<NonceRange>>,
+    stealers: Arc<Vec<Stealer<NonceRange>>>,
+}
+
+// Shared coordination state a worker thread watches or updates while
+// searching, as opposed to the (read-only, per-search) commit data in
+// CommitContext.
+#[derive(Clone)]
+struct SearchState {
+    shared_result: Arc<AtomicU64>,
+    stop_flag: Arc<AtomicBool>,
+    nonce_high_water: Arc<AtomicU64>,
+    // Set in --serve/--connect mode so a hit gets reported/broadcast.
+    coordinator_addr: Option<Arc<String>>,
+}
+
+// Pops the next range of work for this worker: first its own local queue,
+// then the global injector, then a randomly chosen sibling's queue. This is
+// the standard crossbeam-deque steal order, just with the sibling picked at
+// random instead of round-robin so repeated stalls don't hammer one victim.
+fn find_range(queues: &WorkerQueues) -> Option<NonceRange> {
+    queues.local.pop().or_else(|| {
+        std::iter::repeat_with(|| {
+            queues.injector.steal_batch_and_pop(&queues.local).or_else(|| {
+                if queues.stealers.is_empty() {
+                    Steal::Empty
+                } else {
+                    let victim = rand::thread_rng().gen_range(0..queues.stealers.len());
+                    queues.stealers[victim].steal()
+                }
+            })
+        })
+        .find(|steal| !steal.is_retry())
+        .and_then(|steal| steal.success())
+    })
+}
+
 fn thread_logic(
-    raw_header: &str,
-    raw_message: &str,
-    committer_name: &str,
-    number: Arc<AtomicU64>,
+    ctx: &CommitContext,
+    queues: WorkerQueues,
     desired_hex_value: &str,
-    shared_result: Arc<AtomicU64>,
     hidden_message: Option<&str>, // Pass hidden message to thread logic
+    state: SearchState,
 ) {
     loop {
-        // Check if another thread has already populated the result
-        if shared_result.load(Ordering::SeqCst) != 0 {
+        // Check if another thread has already populated the result, or the
+        // user hit Ctrl-C and asked everyone to stop
+        if state.shared_result.load(Ordering::SeqCst) != 0 || state.stop_flag.load(Ordering::SeqCst) {
             return; // Exit early if the result is already set
         }
 
-        // Call the function
-        let number_under_test = number.fetch_add(100, Ordering::SeqCst);
-        for i in 0..99 {
-            if check_commit_with_nonce(
-                raw_header,
-                raw_message,
-                committer_name,
-                number_under_test + i,
-                desired_hex_value,
-                hidden_message,
-            ) {
-                let _ = shared_result.compare_exchange(
+        let range = match find_range(&queues) {
+            Some(range) => range,
+            None => {
+                // No work available right now; the generator may just be
+                // behind. Back off briefly and try again.
+                thread::sleep(Duration::from_millis(1));
+                continue;
+            }
+        };
+
+        // Walk the owned range locally -- no atomics except the early-exit
+        // check and the high-water mark used for progress reporting.
+        for candidate in range.start..range.end {
+            if state.shared_result.load(Ordering::SeqCst) != 0 || state.stop_flag.load(Ordering::SeqCst) {
+                return;
+            }
+
+            if check_commit_with_nonce(ctx, candidate, desired_hex_value, hidden_message) {
+                let won = state.shared_result.compare_exchange(
                     0,
-                    number_under_test + i,
+                    candidate,
                     Ordering::SeqCst,
                     Ordering::SeqCst,
-                ).is_ok(); // If successful, exit the thread
+                ).is_ok();
+                if won {
+                    // Tell the coordinator (or, in --serve mode, ourselves)
+                    // so every remote worker gets told to stop too.
+                    if let Some(addr) = &state.coordinator_addr {
+                        net::report_found(addr, candidate);
+                    }
+                }
                 return;
             }
+
+            // Bump the high-water mark periodically, not just at the end of
+            // the range, so a Ctrl-C mid-range still leaves an accurate
+            // checkpoint behind.
+            if candidate % PROGRESS_REPORT_INTERVAL == 0 {
+                state.nonce_high_water.fetch_max(candidate, Ordering::SeqCst);
+            }
         }
+
+        // Bump the high-water mark so the progress monitor can keep
+        // reporting hashes/sec without the workers touching a shared atomic
+        // on every single hash.
+        state.nonce_high_water.fetch_max(range.end, Ordering::SeqCst);
     }
 }
 
-fn is_valid_hex(value: &str) -> bool {
-    !value.is_empty() && value.chars().all(|c| c.is_digit(16))
+fn is_valid_hex(value: &str, max_len: usize) -> bool {
+    !value.is_empty() && value.len() <= max_len && value.chars().all(|c| c.is_digit(16))
 }
 
 fn get_argument_value(args: &[String], flag: &str) -> Option<String> {
@@ -144,11 +344,26 @@ fn sanitize_raw_header(raw_header: &str, committer_name_raw: &str, committer_nam
 fn main() {
     let args: Vec<String> = env::args().collect();
 
+    // Open the repo and resolve the hash algorithm first: --hash wins if
+    // given (an unrecognised value is an error and exits), otherwise
+    // auto-detect from the repo's object format.
+    // Everything below (including -h/-m validation) depends on knowing this.
+    let repo = Repository::open(".").expect("Failed to open Git repository");
+    let hash_algo = match get_argument_value(&args, "--hash") {
+        Some(value) => HashAlgo::parse(&value).unwrap_or_else(|| {
+            println!("Error: Invalid --hash parameter '{}'. Supported values: sha1, sha256.", value);
+            println!("Usage: cargo run --release -- [-h <hash_prefix>] [-m <hidden_message>] [-n <starting_nonce>] [-j <num_threads>] [--hash <sha1|sha256>]");
+            std::process::exit(1);
+        }),
+        None => HashAlgo::detect(&repo),
+    };
+    println!("Using hash algorithm: {:?}", hash_algo);
+
     // Parse the -h parameter for the desired hash prefix
-    let hex_parameter = get_argument_value(&args, "-h").filter(|valid_hex| is_valid_hex(valid_hex));
+    let hex_parameter = get_argument_value(&args, "-h").filter(|valid_hex| is_valid_hex(valid_hex, hash_algo.hex_width()));
 
     // Parse the -m parameter for the hidden message
-    let hidden_message = get_argument_value(&args, "-m").filter(|hidden| is_valid_hex(hidden));
+    let hidden_message = get_argument_value(&args, "-m").filter(|hidden| is_valid_hex(hidden, hash_algo.hex_width()));
 
     // Parse the -n parameter for the starting nonce (hexadecimal only, default to 1 if not provided)
     let starting_nonce = get_argument_value(&args, "-n")
@@ -176,6 +391,16 @@ fn main() {
         num_cpus::get()
     };
 
+    // Parse --serve/--connect for distributed mining. They're mutually
+    // exclusive: --serve runs a coordinator (plus its own local miners),
+    // --connect joins an existing one instead of generating nonces locally.
+    let serve_addr = get_argument_value(&args, "--serve");
+    let connect_addr = get_argument_value(&args, "--connect");
+    if serve_addr.is_some() && connect_addr.is_some() {
+        println!("Error: --serve and --connect cannot be used together.");
+        return;
+    }
+
     if let Some(ref hex) = hex_parameter {
         println!("Searching for hash starting with: {}", hex);
     }
@@ -184,11 +409,26 @@ fn main() {
     }
     println!("Starting nonce: {:X}", starting_nonce); // Display the starting nonce in hex
     println!("Using {} threads.", num_threads);
+    if let Some(ref addr) = serve_addr {
+        println!("Serving nonce leases to remote workers on {}", addr);
+    }
+    if let Some(ref addr) = connect_addr {
+        println!("Connecting to coordinator at {} for nonce leases", addr);
+    }
 
     let hex_value = Arc::new(hex_parameter.unwrap_or_default());
     let hidden_message = Arc::new(hidden_message); // Share hidden message across threads
-    let nonce = Arc::new(AtomicU64::new(starting_nonce)); // Use the starting nonce
-    let repo = Repository::open(".").expect("Failed to open Git repository");
+    // One low-water marker per worker, not a single shared high-water mark:
+    // ranges are handed out in increasing order but workers race through
+    // them concurrently and out of order (stealing included), so the
+    // highest nonce *any* worker has reached says nothing about whether a
+    // straggler is still sitting on an un-hashed range far behind. Each
+    // worker only ever advances its own marker past nonces it has actually
+    // verified, so the minimum across all of them is a true safe resume
+    // point -- nothing below it can still be outstanding.
+    let nonce_markers: Vec<Arc<AtomicU64>> = (0..num_threads)
+        .map(|_| Arc::new(AtomicU64::new(starting_nonce)))
+        .collect();
     let head = repo.head().expect("Failed to get HEAD reference");
     let commit = head.peel_to_commit().expect("Failed to resolve HEAD to commit");
     let raw_header_raw = commit.raw_header().unwrap_or("No RAW bytes").to_string();
@@ -198,14 +438,52 @@ fn main() {
     let committer_name = Arc::new(sanitize_committer_name(&committer_name_raw));
     let raw_header = Arc::new(sanitize_raw_header(&raw_header_raw, &committer_name_raw, &committer_name));
     let shared_result = Arc::new(AtomicU64::new(0)); // Now an AtomicU64
+    let stop_flag = Arc::new(AtomicBool::new(false)); // Flipped by the Ctrl-C handler below
+    let ctx = CommitContext {
+        raw_header: Arc::clone(&raw_header),
+        raw_message: Arc::clone(&raw_message),
+        committer_name: Arc::clone(&committer_name),
+        hash_algo,
+    };
 
-    // Start a monitoring thread to display nonce increase per 5 seconds
-    let nonce_clone = Arc::clone(&nonce);
+    // In --serve mode a local hit must be reported back through the
+    // coordinator so it gets broadcast to every connected remote worker; in
+    // --connect mode it's reported to the real coordinator. Plain local runs
+    // have nobody to tell.
+    let coordinator_addr: Option<Arc<String>> = serve_addr
+        .clone()
+        .or_else(|| connect_addr.clone())
+        .map(Arc::new);
+
+    // Install a Ctrl-C handler so an interrupted run can be resumed later:
+    // flip the stop flag so every worker and the generator exit cleanly,
+    // instead of losing whatever progress has been made.
+    let ctrlc_stop_flag = Arc::clone(&stop_flag);
+    ctrlc::set_handler(move || {
+        ctrlc_stop_flag.store(true, Ordering::SeqCst);
+    })
+    .expect("Failed to set Ctrl-C handler");
+
+    // Set up the work-stealing deque pool: one local queue per worker, plus
+    // a global injector the generator feeds and workers fall back to.
+    let injector = Arc::new(Injector::new());
+    let workers: Vec<Worker<NonceRange>> = (0..num_threads).map(|_| Worker::new_lifo()).collect();
+    let stealers: Arc<Vec<Stealer<NonceRange>>> = Arc::new(workers.iter().map(|w| w.stealer()).collect());
+
+    // Start a monitoring thread to display nonce increase per 5 seconds. The
+    // fastest worker's marker is the most representative "how far have we
+    // gotten" number for this display; the resume checkpoint below uses the
+    // minimum instead, since that one has to be safe, not just informative.
+    let nonce_markers_clone = nonce_markers.clone();
     thread::spawn(move || {
         let mut previous_nonce = starting_nonce;
         loop {
             thread::sleep(Duration::from_secs(5));
-            let current_nonce = nonce_clone.load(Ordering::SeqCst);
+            let current_nonce = nonce_markers_clone
+                .iter()
+                .map(|marker| marker.load(Ordering::SeqCst))
+                .max()
+                .unwrap_or(starting_nonce);
             let hashes_per_second = (current_nonce - previous_nonce) / 5; // Average over 5 seconds
             previous_nonce = current_nonce;
 
@@ -215,25 +493,79 @@ fn main() {
         }
     });
 
+    // The cursor hands out CHUNK_SIZE-wide nonce ranges; in --serve mode it's
+    // shared with the coordinator so local and remote workers never overlap.
+    let cursor = Arc::new(AtomicU64::new(starting_nonce));
+
+    if let Some(ref addr) = connect_addr {
+        // --connect mode: don't run a local generator at all. Instead, pull
+        // leases from the coordinator and feed them into our own injector,
+        // so the rest of the work-stealing pipeline is none the wiser.
+        let addr = addr.clone();
+        let network_injector = Arc::clone(&injector);
+        let network_result = Arc::clone(&shared_result);
+        let network_stop_flag = Arc::clone(&stop_flag);
+        thread::spawn(move || {
+            net::run_network_generator(&addr, network_injector, network_result, network_stop_flag, num_threads);
+        });
+    } else {
+        // Local mode (plain, or --serve): start the generator thread, which
+        // feeds the injector with nonce ranges drawn from `cursor`.
+        let generator_cursor = Arc::clone(&cursor);
+        let generator_injector = Arc::clone(&injector);
+        let generator_result = Arc::clone(&shared_result);
+        let generator_stop_flag = Arc::clone(&stop_flag);
+        thread::spawn(move || {
+            generator_logic(generator_cursor, generator_injector, generator_result, generator_stop_flag, num_threads);
+        });
+    }
+
+    if let Some(ref addr) = serve_addr {
+        // --serve mode: also serve nonce-range leases to remote workers out
+        // of the same cursor, and verify/broadcast whatever they report found.
+        let addr = addr.clone();
+        let coordinator_cursor = Arc::clone(&cursor);
+        let coordinator_result = Arc::clone(&shared_result);
+        let coordinator_ctx = ctx.clone();
+        let coordinator_hex_value = Arc::clone(&hex_value);
+        let coordinator_hidden_message = Arc::clone(&hidden_message);
+        thread::spawn(move || {
+            net::run_coordinator(
+                &addr,
+                coordinator_cursor,
+                coordinator_result,
+                coordinator_ctx,
+                coordinator_hex_value,
+                coordinator_hidden_message,
+            );
+        });
+    }
+
     let mut handles = vec![];
-    for _ in 0..num_threads {
+    for (local, nonce_marker) in workers.into_iter().zip(nonce_markers.iter()) {
         let hex_value_clone = Arc::clone(&hex_value);
         let hidden_message_clone = Arc::clone(&hidden_message);
-        let shared_result_clone = Arc::clone(&shared_result);
-        let raw_header_clone = Arc::clone(&raw_header);
-        let raw_message_clone = Arc::clone(&raw_message);
-        let committer_name_clone = Arc::clone(&committer_name);
-        let nonce_clone = Arc::clone(&nonce);
+        let injector_clone = Arc::clone(&injector);
+        let stealers_clone = Arc::clone(&stealers);
+        let ctx_clone = ctx.clone();
+        let state = SearchState {
+            shared_result: Arc::clone(&shared_result),
+            stop_flag: Arc::clone(&stop_flag),
+            nonce_high_water: Arc::clone(nonce_marker),
+            coordinator_addr: coordinator_addr.clone(),
+        };
 
         let handle = thread::spawn(move || {
             thread_logic(
-                &raw_header_clone,
-                &raw_message_clone,
-                &committer_name_clone,
-                nonce_clone,
+                &ctx_clone,
+                WorkerQueues {
+                    local,
+                    injector: injector_clone,
+                    stealers: stealers_clone,
+                },
                 &hex_value_clone,
-                shared_result_clone,
                 hidden_message_clone.as_deref(), // Pass hidden message
+                state,
             );
         });
         handles.push(handle);
@@ -246,9 +578,34 @@ fn main() {
     let final_result = shared_result.load(Ordering::SeqCst);
     if final_result != 0 {
         println!("A thread found: {:X}", final_result);
+        // Real hit: skip the checkpoint message below and go straight to amending.
+    } else if stop_flag.load(Ordering::SeqCst) {
+        // The safe resume point is the minimum across workers, not the
+        // maximum: a straggler's marker means nothing below it has been
+        // verified yet, no matter how far ahead the other workers got.
+        let resume_from = nonce_markers
+            .iter()
+            .map(|marker| marker.load(Ordering::SeqCst))
+            .min()
+            .unwrap_or(starting_nonce);
+        println!("Interrupted. Lowest nonce reached across workers: {:X}", resume_from);
+        println!("Resume with: -n {:X}", resume_from);
     } else {
         println!("No thread returned a result.");
     }
 
-    let _ = build_commit_with_nonce(&commit, final_result);
+    // Only amend on a genuine hit. final_result == 0 covers both a user
+    // interrupt and (in principle) threads exhausting their work with
+    // nothing found -- there's nothing valid to commit in either case.
+    if final_result != 0 {
+        if let Err(e) = build_commit_with_nonce(
+            &commit,
+            final_result,
+            &ctx,
+            &hex_value,
+            hidden_message.as_deref(),
+        ) {
+            eprintln!("Error: failed to amend commit: {}", e);
+        }
+    }
 }
@@ -0,0 +1,254 @@
+// Coordinator/worker protocol for distributed mining (--serve / --connect).
+//
+// The wire format is deliberately plain text, line-based, one message per
+// line -- the same "just parse the string" approach the rest of this crate
+// uses for its CLI flags, rather than pulling in a serialization crate for
+// three message shapes:
+//
+//   worker -> coordinator   "LEASE"
+//   coordinator -> worker   "RANGE <start_hex> <end_hex>"
+//   worker -> coordinator   "FOUND <nonce_hex>"
+//   coordinator -> worker   "STOP <nonce_hex>"   (broadcast once verified)
+//   coordinator -> worker   "INVALID"            (reported nonce didn't hash)
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crossbeam_deque::Injector;
+
+use crate::{check_commit_with_nonce, CommitContext, NonceRange, CHUNK_SIZE};
+
+// A connection's outgoing half, mutex-guarded so a LEASE/FOUND reply from
+// this connection's own handler thread and a STOP broadcast triggered by a
+// *different* connection's handler thread can never interleave their writes
+// on the wire -- writeln! issues more than one write() syscall per line, so
+// without this two concurrent writers can garble each other's line mid-way.
+type SharedWriter = Arc<Mutex<TcpStream>>;
+
+// Connections the coordinator has accepted so far, plus the one-shot flag
+// that guards broadcast_stop. Bundled together since every worker connection
+// handler needs both and neither is ever passed on its own.
+struct CoordinatorState {
+    connections: Mutex<Vec<SharedWriter>>,
+    // Guards broadcast_stop so it fires exactly once, independent of whether
+    // this coordinator's own compare_exchange on shared_result is the one
+    // that wins -- a local mining hit sets shared_result directly (see
+    // main.rs thread_logic) before ever reaching here via report_found, so
+    // gating the broadcast on that compare_exchange succeeding would miss
+    // the most common win path entirely.
+    broadcast_done: AtomicBool,
+}
+
+// Runs the coordinator side of --serve: accepts worker connections, hands
+// each one CHUNK_SIZE-wide nonce ranges cut from the shared `cursor`, and
+// verifies/broadcasts whatever a worker reports finding. Never returns --
+// the caller runs it on its own thread alongside its local mining.
+pub fn run_coordinator(
+    addr: &str,
+    cursor: Arc<AtomicU64>,
+    shared_result: Arc<AtomicU64>,
+    ctx: CommitContext,
+    desired_hex_value: Arc<String>,
+    hidden_message: Arc<Option<String>>,
+) {
+    let listener = TcpListener::bind(addr).expect("Failed to bind --serve address");
+    let state = Arc::new(CoordinatorState {
+        connections: Mutex::new(Vec::new()),
+        broadcast_done: AtomicBool::new(false),
+    });
+
+    for incoming in listener.incoming() {
+        let stream = match incoming {
+            Ok(stream) => stream,
+            Err(_) => continue,
+        };
+
+        let cursor = Arc::clone(&cursor);
+        let shared_result = Arc::clone(&shared_result);
+        let ctx = ctx.clone();
+        let desired_hex_value = Arc::clone(&desired_hex_value);
+        let hidden_message = Arc::clone(&hidden_message);
+        let state = Arc::clone(&state);
+
+        thread::spawn(move || {
+            handle_worker_connection(
+                stream,
+                cursor,
+                shared_result,
+                ctx,
+                desired_hex_value,
+                hidden_message,
+                state,
+            );
+        });
+    }
+}
+
+fn handle_worker_connection(
+    stream: TcpStream,
+    cursor: Arc<AtomicU64>,
+    shared_result: Arc<AtomicU64>,
+    ctx: CommitContext,
+    desired_hex_value: Arc<String>,
+    hidden_message: Arc<Option<String>>,
+    state: Arc<CoordinatorState>,
+) {
+    let mut reader = match stream.try_clone() {
+        Ok(clone) => BufReader::new(clone),
+        Err(_) => return,
+    };
+    let writer: SharedWriter = Arc::new(Mutex::new(stream));
+    state.connections.lock().unwrap().push(Arc::clone(&writer));
+    let mut line = String::new();
+
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).unwrap_or(0) == 0 {
+            return; // Worker disconnected
+        }
+        let message = line.trim();
+
+        if message == "LEASE" {
+            let already_found = shared_result.load(Ordering::SeqCst);
+            if already_found != 0 {
+                let _ = writeln!(writer.lock().unwrap(), "STOP {:x}", already_found);
+                return;
+            }
+            let start = cursor.fetch_add(CHUNK_SIZE, Ordering::SeqCst);
+            if writeln!(writer.lock().unwrap(), "RANGE {:x} {:x}", start, start + CHUNK_SIZE).is_err() {
+                return;
+            }
+        } else if let Some(nonce_hex) = message.strip_prefix("FOUND ") {
+            let nonce = match u64::from_str_radix(nonce_hex.trim(), 16) {
+                Ok(nonce) => nonce,
+                Err(_) => {
+                    let _ = writeln!(writer.lock().unwrap(), "INVALID");
+                    continue;
+                }
+            };
+
+            // Never trust a worker's say-so: re-hash it ourselves before
+            // accepting it, so a buggy or malicious worker can't poison the
+            // result.
+            let verified = check_commit_with_nonce(&ctx, nonce, &desired_hex_value, hidden_message.as_deref());
+
+            if !verified {
+                let _ = writeln!(writer.lock().unwrap(), "INVALID");
+                continue;
+            }
+
+            // Don't gate the broadcast on *this* compare_exchange winning --
+            // a local mining thread may have already set shared_result to
+            // this very nonce before this report ever arrived, so the
+            // exchange below is expected to lose in the common case. What
+            // matters is that a result is known and hasn't been broadcast
+            // yet.
+            let _ = shared_result.compare_exchange(0, nonce, Ordering::SeqCst, Ordering::SeqCst);
+            if !state.broadcast_done.swap(true, Ordering::SeqCst) {
+                broadcast_stop(&state.connections, shared_result.load(Ordering::SeqCst));
+            }
+            return;
+        } else {
+            return; // Unknown message, drop the connection
+        }
+    }
+}
+
+fn broadcast_stop(connections: &Mutex<Vec<SharedWriter>>, nonce: u64) {
+    let streams = connections.lock().unwrap();
+    for writer in streams.iter() {
+        let _ = writeln!(writer.lock().unwrap(), "STOP {:x}", nonce);
+    }
+}
+
+// Worker side of --connect: pulls nonce-range leases from a coordinator and
+// pushes them onto the local injector exactly as the local generator thread
+// would, so the rest of the work-stealing pipeline doesn't care whether its
+// ranges came from this machine or a remote one.
+pub fn run_network_generator(
+    addr: &str,
+    injector: Arc<Injector<NonceRange>>,
+    shared_result: Arc<AtomicU64>,
+    stop_flag: Arc<AtomicBool>,
+    num_workers: usize,
+) {
+    let stream = match TcpStream::connect(addr) {
+        Ok(stream) => stream,
+        Err(e) => {
+            // This runs on its own thread, so panicking here would only
+            // kill this thread silently -- the generator never arrives,
+            // and every worker spins forever with no ranges to pull.
+            // Instead, report it and stop the whole run via the stop flag.
+            eprintln!("Error: failed to connect to --connect coordinator at {}: {}", addr, e);
+            stop_flag.store(true, Ordering::SeqCst);
+            return;
+        }
+    };
+    let mut reader = match stream.try_clone() {
+        Ok(clone) => BufReader::new(clone),
+        Err(_) => {
+            stop_flag.store(true, Ordering::SeqCst);
+            return;
+        }
+    };
+    let mut writer = stream;
+    let mut line = String::new();
+
+    loop {
+        if shared_result.load(Ordering::SeqCst) != 0 || stop_flag.load(Ordering::SeqCst) {
+            return;
+        }
+
+        // Don't let the backlog grow unbounded if workers fall behind.
+        if injector.len() > num_workers * 4 {
+            thread::sleep(Duration::from_millis(10));
+            continue;
+        }
+
+        if writeln!(writer, "LEASE").is_err() {
+            stop_flag.store(true, Ordering::SeqCst);
+            return;
+        }
+
+        line.clear();
+        if reader.read_line(&mut line).unwrap_or(0) == 0 {
+            stop_flag.store(true, Ordering::SeqCst);
+            return;
+        }
+        let message = line.trim();
+
+        if let Some(rest) = message.strip_prefix("RANGE ") {
+            let mut parts = rest.split_whitespace();
+            if let (Some(start_hex), Some(end_hex)) = (parts.next(), parts.next()) {
+                if let (Ok(start), Ok(end)) = (u64::from_str_radix(start_hex, 16), u64::from_str_radix(end_hex, 16)) {
+                    injector.push(NonceRange { start, end });
+                }
+            }
+        } else if let Some(nonce_hex) = message.strip_prefix("STOP") {
+            if let Ok(nonce) = u64::from_str_radix(nonce_hex.trim(), 16) {
+                let _ = shared_result.compare_exchange(0, nonce, Ordering::SeqCst, Ordering::SeqCst);
+            }
+            stop_flag.store(true, Ordering::SeqCst);
+            return;
+        } else {
+            stop_flag.store(true, Ordering::SeqCst);
+            return;
+        }
+    }
+}
+
+// Reports a hit back to the coordinator on a short-lived connection, kept
+// separate from the lease-pulling connection so a slow coordinator response
+// never blocks the next lease request.
+pub fn report_found(addr: &str, nonce: u64) {
+    if let Ok(mut stream) = TcpStream::connect(addr) {
+        let _ = writeln!(stream, "FOUND {:x}", nonce);
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        let _ = reader.read_line(&mut line);
+    }
+}